@@ -0,0 +1,234 @@
+//! Batches deserialized packets for forwarding to the next leader in
+//! descending priority order, respecting per-account and per-block cost
+//! limits.
+
+use {
+    super::immutable_deserialized_packet::ImmutableDeserializedPacket,
+    solana_perf::packet::PacketBatch,
+    solana_sdk::{pubkey::Pubkey, saturating_add_assign},
+    std::collections::HashMap,
+};
+
+/// Number of forwarding batches to build. Packets that cannot fit into any
+/// of them are retained by the caller for a later forwarding round rather
+/// than dropped.
+pub const DEFAULT_NUMBER_OF_FORWARD_BATCHES: usize = 2;
+
+/// Default per-account cost limit for a single forwarding batch, mirroring
+/// the leader's per-account write-lock cost limit.
+pub const DEFAULT_ACCOUNT_COST_LIMIT: u64 = 12_000_000;
+
+/// Default total cost limit for a single forwarding batch, mirroring the
+/// leader's per-block cost limit.
+pub const DEFAULT_BLOCK_COST_LIMIT: u64 = 48_000_000;
+
+/// A single forwarding batch with its own per-account and block cost
+/// accounting.
+#[derive(Default)]
+struct ForwardBatch {
+    account_costs: HashMap<Pubkey, u64>,
+    block_cost: u64,
+    packets: Vec<ImmutableDeserializedPacket>,
+}
+
+impl ForwardBatch {
+    /// Tries to account for `packet` in this batch. Returns `false` without
+    /// mutating any state if adding it would push any write-locked account,
+    /// or the batch as a whole, past `account_cost_limit`/`block_cost_limit`.
+    fn try_add(
+        &mut self,
+        packet: &ImmutableDeserializedPacket,
+        account_cost_limit: u64,
+        block_cost_limit: u64,
+    ) -> bool {
+        let cost = packet.compute_unit_limit();
+
+        if self.block_cost.saturating_add(cost) > block_cost_limit {
+            return false;
+        }
+
+        let message = packet.transaction().get_message();
+        let account_keys = message.message.static_account_keys();
+        let write_locked_accounts: Vec<&Pubkey> = account_keys
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| message.is_writable(*index))
+            .map(|(_, key)| key)
+            .collect();
+
+        for account in &write_locked_accounts {
+            let projected_cost = self
+                .account_costs
+                .get(*account)
+                .copied()
+                .unwrap_or(0)
+                .saturating_add(cost);
+            if projected_cost > account_cost_limit {
+                return false;
+            }
+        }
+
+        for account in write_locked_accounts {
+            let entry = self.account_costs.entry(*account).or_insert(0);
+            saturating_add_assign!(*entry, cost);
+        }
+        saturating_add_assign!(self.block_cost, cost);
+        self.packets.push(packet.clone());
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+#[derive(Default)]
+pub struct ForwardPacketBatchesByAccountsStats {
+    pub forwardable_packets_count: u64,
+    pub forwardable_batches_count: u64,
+}
+
+/// Buckets packets for forwarding by the accounts they write-lock, draining
+/// them highest-priority-first and skipping (but not dropping) any packet
+/// that would exceed a batch's per-account or block cost limits.
+pub struct ForwardPacketBatchesByAccounts {
+    batches: Vec<ForwardBatch>,
+    account_cost_limit: u64,
+    block_cost_limit: u64,
+}
+
+impl ForwardPacketBatchesByAccounts {
+    pub fn new(number_of_batches: usize, account_cost_limit: u64, block_cost_limit: u64) -> Self {
+        let number_of_batches = number_of_batches.max(1);
+        Self {
+            batches: (0..number_of_batches).map(|_| ForwardBatch::default()).collect(),
+            account_cost_limit,
+            block_cost_limit,
+        }
+    }
+
+    /// Builds the set of forwarding batches for `packets`, assumed to
+    /// already be sorted from highest to lowest priority.
+    pub fn new_from_packets<'a>(
+        packets: impl Iterator<Item = &'a ImmutableDeserializedPacket>,
+        number_of_batches: usize,
+        account_cost_limit: u64,
+        block_cost_limit: u64,
+    ) -> Self {
+        let mut forward_batches =
+            Self::new(number_of_batches, account_cost_limit, block_cost_limit);
+        for packet in packets {
+            forward_batches.add_packet(packet);
+        }
+        forward_batches
+    }
+
+    /// Attempts to place `packet` into the first batch that has room for
+    /// it. Returns `true` if the packet was accepted into some batch, or
+    /// `false` if it did not fit in any of them and should be retained by
+    /// the caller for a later forwarding round.
+    pub fn add_packet(&mut self, packet: &ImmutableDeserializedPacket) -> bool {
+        for batch in self.batches.iter_mut() {
+            if batch.try_add(packet, self.account_cost_limit, self.block_cost_limit) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Consumes the batches, returning one `PacketBatch` per forwarding
+    /// batch that is non-empty.
+    pub fn take_batches(self) -> Vec<PacketBatch> {
+        self.batches
+            .into_iter()
+            .filter(|batch| batch.len() > 0)
+            .map(|batch| {
+                PacketBatch::from(
+                    batch
+                        .packets
+                        .iter()
+                        .map(|packet| packet.original_packet().clone())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> ForwardPacketBatchesByAccountsStats {
+        let forwardable_batches_count = self.batches.iter().filter(|b| b.len() > 0).count() as u64;
+        let forwardable_packets_count = self.batches.iter().map(|b| b.len() as u64).sum();
+        ForwardPacketBatchesByAccountsStats {
+            forwardable_packets_count,
+            forwardable_batches_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_perf::packet::Packet,
+        solana_sdk::{
+            compute_budget::ComputeBudgetInstruction, hash::Hash, message::Message,
+            signature::Keypair, signer::Signer, system_instruction, transaction::Transaction,
+        },
+    };
+
+    fn packet_with_cu_limit(payer: &Keypair, compute_unit_limit: u32) -> ImmutableDeserializedPacket {
+        let instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+            system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1),
+        ];
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[payer], message, Hash::default());
+        let packet = Packet::from_data(None, transaction).unwrap();
+        ImmutableDeserializedPacket::new(&packet).unwrap()
+    }
+
+    #[test]
+    fn test_add_packet_that_fits() {
+        let payer = Keypair::new();
+        let mut batches = ForwardPacketBatchesByAccounts::new(1, 1_000_000, 1_000_000);
+        assert!(batches.add_packet(&packet_with_cu_limit(&payer, 100)));
+        assert_eq!(batches.stats().forwardable_packets_count, 1);
+    }
+
+    #[test]
+    fn test_add_packet_exceeding_account_cost_limit_is_skipped() {
+        let payer = Keypair::new();
+        let mut batches = ForwardPacketBatchesByAccounts::new(1, 100, 1_000_000);
+        assert!(batches.add_packet(&packet_with_cu_limit(&payer, 80)));
+        // Same fee payer is write-locked again; 80 + 80 > account_cost_limit.
+        assert!(!batches.add_packet(&packet_with_cu_limit(&payer, 80)));
+        assert_eq!(batches.stats().forwardable_packets_count, 1);
+    }
+
+    #[test]
+    fn test_add_packet_exceeding_block_cost_limit_is_skipped() {
+        let mut batches = ForwardPacketBatchesByAccounts::new(1, 1_000_000, 100);
+        assert!(batches.add_packet(&packet_with_cu_limit(&Keypair::new(), 80)));
+        // Different fee payer, so only the block limit is at stake.
+        assert!(!batches.add_packet(&packet_with_cu_limit(&Keypair::new(), 80)));
+        assert_eq!(batches.stats().forwardable_packets_count, 1);
+    }
+
+    #[test]
+    fn test_add_packet_overflowing_first_batch_lands_in_second() {
+        let payer = Keypair::new();
+        let mut batches = ForwardPacketBatchesByAccounts::new(2, 100, 1_000_000);
+        assert!(batches.add_packet(&packet_with_cu_limit(&payer, 80)));
+        // Doesn't fit in batch 0 (same payer, 80 + 80 > 100), falls through
+        // to batch 1 instead of being rejected outright.
+        assert!(batches.add_packet(&packet_with_cu_limit(&payer, 80)));
+
+        let stats = batches.stats();
+        assert_eq!(stats.forwardable_packets_count, 2);
+        assert_eq!(stats.forwardable_batches_count, 2);
+
+        let packet_batches = batches.take_batches();
+        assert_eq!(packet_batches.len(), 2);
+        assert_eq!(packet_batches[0].len(), 1);
+        assert_eq!(packet_batches[1].len(), 1);
+    }
+}
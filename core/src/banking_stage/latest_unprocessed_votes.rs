@@ -0,0 +1,159 @@
+//! A vote-aware buffer that retains only the single latest vote (by slot)
+//! from each validator, discarding superseded votes immediately instead of
+//! accumulating them in a growing FIFO. This keeps the buffer fed to the
+//! voting threads tiny and always fresh, which matters most when a
+//! validator is flooded with gossip/TPU votes for slots it has already seen
+//! newer votes for.
+
+use {
+    super::immutable_deserialized_packet::ImmutableDeserializedPacket,
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    solana_vote_program::vote_parser,
+    std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    },
+};
+
+struct LatestValidatorVote {
+    slot: Slot,
+    packet: Arc<ImmutableDeserializedPacket>,
+}
+
+/// Extracts the voting validator's vote account and the latest slot it
+/// votes for out of `packet`, if it is a well-formed vote transaction.
+pub(crate) fn vote_details(packet: &ImmutableDeserializedPacket) -> Option<(Pubkey, Slot)> {
+    let (vote_account_pubkey, vote, ..) =
+        vote_parser::parse_vote_transaction(packet.transaction().get_transaction())?;
+    let slot = *vote.slots().iter().max()?;
+    Some((vote_account_pubkey, slot))
+}
+
+/// Keeps only the most recent vote (by slot) seen from each validator vote
+/// account.
+#[derive(Default)]
+pub struct LatestUnprocessedVotes {
+    latest_votes_per_validator: RwLock<HashMap<Pubkey, LatestValidatorVote>>,
+}
+
+impl LatestUnprocessedVotes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `packet` as the latest vote from `vote_account` for `slot`.
+    /// Returns `true` if it replaced (or was the first) tracked vote for
+    /// that validator, or `false` if a vote for an equal or later slot was
+    /// already tracked, in which case `packet` is dropped as stale.
+    pub fn update_latest_vote(
+        &self,
+        vote_account: Pubkey,
+        slot: Slot,
+        packet: ImmutableDeserializedPacket,
+    ) -> bool {
+        let mut latest_votes = self.latest_votes_per_validator.write().unwrap();
+        match latest_votes.get(&vote_account) {
+            Some(existing) if existing.slot >= slot => false,
+            _ => {
+                latest_votes.insert(
+                    vote_account,
+                    LatestValidatorVote {
+                        slot,
+                        packet: Arc::new(packet),
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Returns the deduplicated set of current votes, at most one per
+    /// validator, for the voting threads to process.
+    pub fn current_votes(&self) -> Vec<Arc<ImmutableDeserializedPacket>> {
+        self.latest_votes_per_validator
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| entry.packet.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.latest_votes_per_validator.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_perf::packet::Packet,
+        solana_sdk::{
+            compute_budget::ComputeBudgetInstruction,
+            hash::Hash,
+            message::Message,
+            signature::{Keypair, Signer},
+            system_instruction,
+            transaction::Transaction,
+        },
+    };
+
+    /// A packet whose `compute_unit_price` acts as a marker so tests can
+    /// tell which of several pushed packets survived.
+    fn packet_with_marker(compute_unit_price: u64) -> ImmutableDeserializedPacket {
+        let payer = Keypair::new();
+        let instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+            system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1),
+        ];
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[&payer], message, Hash::default());
+        let packet = Packet::from_data(None, transaction).unwrap();
+        ImmutableDeserializedPacket::new(&packet).unwrap()
+    }
+
+    #[test]
+    fn test_update_latest_vote_newer_slot_replaces_older() {
+        let votes = LatestUnprocessedVotes::new();
+        let vote_account = Pubkey::new_unique();
+
+        assert!(votes.update_latest_vote(vote_account, 5, packet_with_marker(1)));
+        assert!(votes.update_latest_vote(vote_account, 10, packet_with_marker(2)));
+
+        let current = votes.current_votes();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].compute_unit_price(), 2);
+    }
+
+    #[test]
+    fn test_update_latest_vote_rejects_equal_or_older_slot() {
+        let votes = LatestUnprocessedVotes::new();
+        let vote_account = Pubkey::new_unique();
+
+        assert!(votes.update_latest_vote(vote_account, 10, packet_with_marker(1)));
+        // Equal slot: rejected, original packet kept.
+        assert!(!votes.update_latest_vote(vote_account, 10, packet_with_marker(2)));
+        // Older slot: also rejected.
+        assert!(!votes.update_latest_vote(vote_account, 5, packet_with_marker(3)));
+
+        let current = votes.current_votes();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].compute_unit_price(), 1);
+    }
+
+    #[test]
+    fn test_len_and_is_empty_track_distinct_validators() {
+        let votes = LatestUnprocessedVotes::new();
+        assert!(votes.is_empty());
+
+        votes.update_latest_vote(Pubkey::new_unique(), 1, packet_with_marker(1));
+        votes.update_latest_vote(Pubkey::new_unique(), 1, packet_with_marker(2));
+
+        assert_eq!(votes.len(), 2);
+        assert!(!votes.is_empty());
+    }
+}
@@ -2,28 +2,50 @@
 
 use {
     super::{
+        banking_trace::{BankingTraceWriter, ReplayedRecord, TraceReplaySource},
         immutable_deserialized_packet::{DeserializedPacketError, ImmutableDeserializedPacket},
+        latest_unprocessed_votes::{self, LatestUnprocessedVotes},
         packet_filter::PacketFilterFailure,
+        packet_priority_queue::PacketPriorityQueue,
     },
     agave_banking_stage_ingress_types::{BankingPacketBatch, BankingPacketReceiver},
     crossbeam_channel::RecvTimeoutError,
     solana_perf::packet::PacketBatch,
-    solana_sdk::saturating_add_assign,
-    std::time::{Duration, Instant},
+    solana_sdk::{hash::Hash, saturating_add_assign},
+    std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
 };
 
-/// Results from deserializing packet batches.
+/// Where a [`PacketDeserializer`] pulls its packet batches from.
+enum PacketSource {
+    /// The live sigverify -> banking stage channel.
+    Live(BankingPacketReceiver),
+    /// A previously recorded banking trace, replayed in original order and
+    /// timing instead of reading from the live channel.
+    Replay(Mutex<TraceReplaySource>),
+}
+
+/// Results from deserializing packet batches. Deserialized packets are
+/// pushed directly into the caller's `PacketPriorityQueue`; this only
+/// reports counts of packets received and errors recorded during
+/// deserialization and filtering.
 pub struct ReceivePacketResults {
-    /// Deserialized packets from all received packet batches
-    pub deserialized_packets: Vec<ImmutableDeserializedPacket>,
-    /// Counts of packets received and errors recorded during deserialization
-    /// and filtering
     pub packet_stats: PacketReceiverStats,
 }
 
 pub struct PacketDeserializer {
-    /// Receiver for packet batches from sigverify stage
-    packet_batch_receiver: BankingPacketReceiver,
+    /// Source of packet batches: either the live sigverify channel or a
+    /// recorded trace being replayed
+    packet_source: PacketSource,
+    /// When set, every received packet batch is recorded to this trace log
+    /// before being deserialized
+    trace_writer: Option<Mutex<BankingTraceWriter>>,
+    /// The most recent blockhash override read back while replaying a trace,
+    /// if any, waiting to be claimed by the caller so a simulator can apply
+    /// it before processing the next batch of replayed packets
+    pending_blockhash_override: Mutex<Option<Hash>>,
 }
 
 #[derive(Default, Debug, PartialEq)]
@@ -42,6 +64,14 @@ pub struct PacketReceiverStats {
     pub excessive_precompile_count: u64,
     /// Number of packets dropped due to insufficient compute limit
     pub insufficient_compute_limit_count: u64,
+    /// Number of packets dropped because their compute unit limit could not
+    /// cover the static cost of their builtin instructions
+    pub below_static_builtin_cost_count: u64,
+    /// Number of vote packets received
+    pub votes_received_count: u64,
+    /// Number of vote packets dropped because a vote for the same or a later
+    /// slot from the same validator was already buffered
+    pub votes_pruned_stale_count: u64,
 }
 
 impl PacketReceiverStats {
@@ -67,6 +97,11 @@ impl PacketReceiverStats {
             ) => {
                 saturating_add_assign!(self.insufficient_compute_limit_count, 1);
             }
+            DeserializedPacketError::FailedFilter(
+                PacketFilterFailure::BelowStaticBuiltinCost,
+            ) => {
+                saturating_add_assign!(self.below_static_builtin_cost_count, 1);
+            }
         }
     }
 }
@@ -74,11 +109,55 @@ impl PacketReceiverStats {
 impl PacketDeserializer {
     pub fn new(packet_batch_receiver: BankingPacketReceiver) -> Self {
         Self {
-            packet_batch_receiver,
+            packet_source: PacketSource::Live(packet_batch_receiver),
+            trace_writer: None,
+            pending_blockhash_override: Mutex::new(None),
         }
     }
 
-    /// Handles receiving packet batches from sigverify and returns a vector of deserialized packets
+    /// Creates a deserializer that replays a previously recorded banking
+    /// trace instead of reading from the live sigverify channel.
+    pub fn new_from_trace(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            packet_source: PacketSource::Replay(Mutex::new(TraceReplaySource::open(path)?)),
+            trace_writer: None,
+            pending_blockhash_override: Mutex::new(None),
+        })
+    }
+
+    /// Enables recording every received packet batch to `path` for later
+    /// deterministic replay.
+    pub fn with_trace_recording(mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        self.trace_writer = Some(Mutex::new(BankingTraceWriter::open(path)?));
+        Ok(self)
+    }
+
+    /// Records an explicit blockhash override to the trace log, if
+    /// recording is enabled, so a later replay can reproduce the same
+    /// blockhash a simulator would need to process the upcoming batches
+    /// deterministically.
+    pub fn record_blockhash_override(&self, blockhash: Hash) -> std::io::Result<()> {
+        if let Some(trace_writer) = &self.trace_writer {
+            trace_writer
+                .lock()
+                .unwrap()
+                .record_blockhash_override(blockhash)?;
+        }
+        Ok(())
+    }
+
+    /// Returns and clears the most recent blockhash override read back while
+    /// replaying a trace, if one was recorded ahead of the packets most
+    /// recently returned by `receive_packets`/`receive_and_buffer_votes`.
+    /// Replay callers should apply this before processing those packets so
+    /// that downstream state (e.g. a simulator's bank) matches what produced
+    /// the original trace.
+    pub fn take_blockhash_override(&self) -> Option<Hash> {
+        self.pending_blockhash_override.lock().unwrap().take()
+    }
+
+    /// Handles receiving packet batches from sigverify, deserializing them,
+    /// and feeding the results directly into `buffer` ordered by priority.
     pub fn receive_packets(
         &self,
         recv_timeout: Duration,
@@ -86,6 +165,7 @@ impl PacketDeserializer {
         packet_filter: impl Fn(
             ImmutableDeserializedPacket,
         ) -> Result<ImmutableDeserializedPacket, PacketFilterFailure>,
+        buffer: &mut PacketPriorityQueue,
     ) -> Result<ReceivePacketResults, RecvTimeoutError> {
         let (packet_count, packet_batches) = self.receive_until(recv_timeout, capacity)?;
 
@@ -93,20 +173,84 @@ impl PacketDeserializer {
             packet_count,
             &packet_batches,
             packet_filter,
+            buffer,
         ))
     }
 
-    /// Deserialize packet batches, aggregates tracer packet stats, and collect
-    /// them into ReceivePacketResults
+    /// Handles receiving packet batches of vote transactions from sigverify,
+    /// routing each into `votes` so that only the latest vote from each
+    /// validator is retained.
+    pub fn receive_and_buffer_votes(
+        &self,
+        recv_timeout: Duration,
+        capacity: usize,
+        votes: &LatestUnprocessedVotes,
+    ) -> Result<PacketReceiverStats, RecvTimeoutError> {
+        let (packet_count, packet_batches) = self.receive_until(recv_timeout, capacity)?;
+
+        Ok(Self::deserialize_and_buffer_votes(
+            packet_count,
+            &packet_batches,
+            votes,
+        ))
+    }
+
+    /// Deserializes packet batches of vote transactions and routes each one
+    /// into `votes`, pruning superseded votes from the same validator as it
+    /// goes.
+    fn deserialize_and_buffer_votes(
+        _packet_count: usize,
+        banking_batches: &[BankingPacketBatch],
+        votes: &LatestUnprocessedVotes,
+    ) -> PacketReceiverStats {
+        let mut packet_stats = PacketReceiverStats::default();
+
+        for banking_batch in banking_batches {
+            for packet_batch in banking_batch.iter() {
+                let packet_indexes = Self::generate_packet_indexes(packet_batch);
+                saturating_add_assign!(
+                    packet_stats.passed_sigverify_count,
+                    packet_indexes.len() as u64
+                );
+                saturating_add_assign!(
+                    packet_stats.failed_sigverify_count,
+                    packet_batch.len().saturating_sub(packet_indexes.len()) as u64
+                );
+
+                for packet_index in &packet_indexes {
+                    let packet = packet_batch[*packet_index].clone();
+                    let Ok(packet) = ImmutableDeserializedPacket::new(&packet) else {
+                        saturating_add_assign!(packet_stats.failed_sanitization_count, 1);
+                        continue;
+                    };
+                    let Some((vote_account, slot)) = latest_unprocessed_votes::vote_details(&packet)
+                    else {
+                        saturating_add_assign!(packet_stats.invalid_vote_count, 1);
+                        continue;
+                    };
+
+                    saturating_add_assign!(packet_stats.votes_received_count, 1);
+                    if !votes.update_latest_vote(vote_account, slot, packet) {
+                        saturating_add_assign!(packet_stats.votes_pruned_stale_count, 1);
+                    }
+                }
+            }
+        }
+
+        packet_stats
+    }
+
+    /// Deserializes packet batches, pushes the results into `buffer`
+    /// ordered by priority, and aggregates packet stats.
     fn deserialize_and_collect_packets(
-        packet_count: usize,
+        _packet_count: usize,
         banking_batches: &[BankingPacketBatch],
         packet_filter: impl Fn(
             ImmutableDeserializedPacket,
         ) -> Result<ImmutableDeserializedPacket, PacketFilterFailure>,
+        buffer: &mut PacketPriorityQueue,
     ) -> ReceivePacketResults {
         let mut packet_stats = PacketReceiverStats::default();
-        let mut deserialized_packets = Vec::with_capacity(packet_count);
 
         for banking_batch in banking_batches {
             for packet_batch in banking_batch.iter() {
@@ -121,50 +265,110 @@ impl PacketDeserializer {
                     packet_batch.len().saturating_sub(packet_indexes.len()) as u64
                 );
 
-                deserialized_packets.extend(Self::deserialize_packets(
+                for packet in Self::deserialize_packets(
                     packet_batch,
                     &packet_indexes,
                     &mut packet_stats,
                     &packet_filter,
-                ));
+                ) {
+                    buffer.push(packet);
+                }
             }
         }
 
-        ReceivePacketResults {
-            deserialized_packets,
-            packet_stats,
-        }
+        ReceivePacketResults { packet_stats }
     }
 
-    /// Receives packet batches from sigverify stage with a timeout
+    /// Receives packet batches either from the live sigverify channel or
+    /// from a replayed trace, depending on how this deserializer was
+    /// constructed. Live batches are received with `recv_timeout` and are
+    /// recorded to the trace log first, if recording is enabled. Replayed
+    /// batches are read until `packet_count_upperbound` packets have been
+    /// received or the trace is exhausted; `recv_timeout` is not applied.
     fn receive_until(
         &self,
         recv_timeout: Duration,
         packet_count_upperbound: usize,
     ) -> Result<(usize, Vec<BankingPacketBatch>), RecvTimeoutError> {
-        let start = Instant::now();
+        match &self.packet_source {
+            PacketSource::Live(packet_batch_receiver) => {
+                let start = Instant::now();
 
-        let packet_batches = self.packet_batch_receiver.recv_timeout(recv_timeout)?;
-        let mut num_packets_received = packet_batches
-            .iter()
-            .map(|batch| batch.len())
-            .sum::<usize>();
-        let mut messages = vec![packet_batches];
+                let packet_batches = packet_batch_receiver.recv_timeout(recv_timeout)?;
+                self.record_if_tracing(&packet_batches);
+                let mut num_packets_received = packet_batches
+                    .iter()
+                    .map(|batch| batch.len())
+                    .sum::<usize>();
+                let mut messages = vec![packet_batches];
 
-        while let Ok(packet_batches) = self.packet_batch_receiver.try_recv() {
-            trace!("got more packet batches in packet deserializer");
-            num_packets_received += packet_batches
-                .iter()
-                .map(|batch| batch.len())
-                .sum::<usize>();
-            messages.push(packet_batches);
+                while let Ok(packet_batches) = packet_batch_receiver.try_recv() {
+                    trace!("got more packet batches in packet deserializer");
+                    self.record_if_tracing(&packet_batches);
+                    num_packets_received += packet_batches
+                        .iter()
+                        .map(|batch| batch.len())
+                        .sum::<usize>();
+                    messages.push(packet_batches);
+
+                    if start.elapsed() >= recv_timeout
+                        || num_packets_received >= packet_count_upperbound
+                    {
+                        break;
+                    }
+                }
 
-            if start.elapsed() >= recv_timeout || num_packets_received >= packet_count_upperbound {
-                break;
+                Ok((num_packets_received, messages))
+            }
+            PacketSource::Replay(replay_source) => {
+                let mut replay_source = replay_source.lock().unwrap();
+                let mut num_packets_received = 0;
+                let mut messages = Vec::new();
+
+                while num_packets_received < packet_count_upperbound {
+                    match replay_source
+                        .next_record()
+                        .map_err(|_| RecvTimeoutError::Disconnected)?
+                    {
+                        Some(ReplayedRecord::Packets { banking_batch, .. }) => {
+                            num_packets_received +=
+                                banking_batch.iter().map(|batch| batch.len()).sum::<usize>();
+                            messages.push(banking_batch);
+                        }
+                        Some(ReplayedRecord::BlockhashOverride(blockhash)) => {
+                            *self.pending_blockhash_override.lock().unwrap() = Some(blockhash);
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+
+                if messages.is_empty() {
+                    return Err(RecvTimeoutError::Disconnected);
+                }
+
+                Ok((num_packets_received, messages))
             }
         }
+    }
+
+    fn record_if_tracing(&self, packet_batches: &BankingPacketBatch) {
+        if let Some(trace_writer) = &self.trace_writer {
+            // A packet that failed sigverify is discarded by the sigverify
+            // stage, so a batch only "passed" if none of its packets were
+            // marked for discard.
+            let sigverify_passed = packet_batches
+                .iter()
+                .all(|batch| batch.iter().all(|packet| !packet.meta().discard()));
 
-        Ok((num_packets_received, messages))
+            if let Err(err) = trace_writer
+                .lock()
+                .unwrap()
+                .record_packets(packet_batches, sigverify_passed)
+            {
+                warn!("failed to record banking trace: {err}");
+            }
+        }
     }
 
     fn generate_packet_indexes(packet_batch: &PacketBatch) -> Vec<usize> {
@@ -219,19 +423,33 @@ mod tests {
         super::*,
         solana_perf::packet::to_packet_batches,
         solana_sdk::{
-            hash::Hash, pubkey::Pubkey, signature::Keypair, system_transaction,
+            clock::Slot, hash::Hash, pubkey::Pubkey, signature::Keypair, system_transaction,
             transaction::Transaction,
         },
+        solana_vote_program::vote_transaction,
     };
 
     fn random_transfer() -> Transaction {
         system_transaction::transfer(&Keypair::new(), &Pubkey::new_unique(), 1, Hash::default())
     }
 
+    fn vote_transaction_for_slot(vote_keypair: &Keypair, slot: Slot) -> Transaction {
+        vote_transaction::new_vote_transaction(
+            vec![slot],
+            Hash::default(),
+            Hash::default(),
+            &Keypair::new(),
+            vote_keypair,
+            vote_keypair,
+            None,
+        )
+    }
+
     #[test]
     fn test_deserialize_and_collect_packets_empty() {
-        let results = PacketDeserializer::deserialize_and_collect_packets(0, &[], Ok);
-        assert_eq!(results.deserialized_packets.len(), 0);
+        let mut buffer = PacketPriorityQueue::with_capacity(16);
+        let results = PacketDeserializer::deserialize_and_collect_packets(0, &[], Ok, &mut buffer);
+        assert_eq!(buffer.len(), 0);
         assert_eq!(results.packet_stats.passed_sigverify_count, 0);
         assert_eq!(results.packet_stats.failed_sigverify_count, 0);
     }
@@ -243,12 +461,14 @@ mod tests {
         assert_eq!(packet_batches.len(), 2);
 
         let packet_count: usize = packet_batches.iter().map(|x| x.len()).sum();
+        let mut buffer = PacketPriorityQueue::with_capacity(16);
         let results = PacketDeserializer::deserialize_and_collect_packets(
             packet_count,
             &[BankingPacketBatch::new(packet_batches)],
             Ok,
+            &mut buffer,
         );
-        assert_eq!(results.deserialized_packets.len(), 2);
+        assert_eq!(buffer.len(), 2);
         assert_eq!(results.packet_stats.passed_sigverify_count, 2);
         assert_eq!(results.packet_stats.failed_sigverify_count, 0);
     }
@@ -261,13 +481,101 @@ mod tests {
         packet_batches[0][0].meta_mut().set_discard(true);
 
         let packet_count: usize = packet_batches.iter().map(|x| x.len()).sum();
+        let mut buffer = PacketPriorityQueue::with_capacity(16);
         let results = PacketDeserializer::deserialize_and_collect_packets(
             packet_count,
             &[BankingPacketBatch::new(packet_batches)],
             Ok,
+            &mut buffer,
         );
-        assert_eq!(results.deserialized_packets.len(), 1);
+        assert_eq!(buffer.len(), 1);
         assert_eq!(results.packet_stats.passed_sigverify_count, 1);
         assert_eq!(results.packet_stats.failed_sigverify_count, 1);
     }
+
+    #[test]
+    fn test_trace_recording_and_replay_round_trip() {
+        let trace_path = std::env::temp_dir().join(format!(
+            "packet_deserializer_trace_test_{}.bin",
+            std::process::id()
+        ));
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let deserializer = PacketDeserializer::new(receiver)
+            .with_trace_recording(&trace_path)
+            .unwrap();
+        deserializer
+            .record_blockhash_override(Hash::new_unique())
+            .unwrap();
+
+        let packet_batches = to_packet_batches(&[random_transfer()], 1);
+        sender
+            .send(BankingPacketBatch::new(packet_batches))
+            .unwrap();
+
+        let mut buffer = PacketPriorityQueue::with_capacity(16);
+        deserializer
+            .receive_packets(Duration::from_millis(100), 16, Ok, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer.len(), 1);
+        drop(sender);
+        drop(deserializer);
+
+        let replay_deserializer = PacketDeserializer::new_from_trace(&trace_path).unwrap();
+        let mut replay_buffer = PacketPriorityQueue::with_capacity(16);
+        replay_deserializer
+            .receive_packets(Duration::from_millis(100), 16, Ok, &mut replay_buffer)
+            .unwrap();
+        assert_eq!(replay_buffer.len(), 1);
+        assert!(replay_deserializer.take_blockhash_override().is_some());
+        assert!(replay_deserializer.take_blockhash_override().is_none());
+
+        std::fs::remove_file(&trace_path).ok();
+    }
+
+    #[test]
+    fn test_deserialize_and_buffer_votes_prunes_stale_vote() {
+        let vote_keypair = Keypair::new();
+        let transactions = vec![
+            vote_transaction_for_slot(&vote_keypair, 5),
+            vote_transaction_for_slot(&vote_keypair, 3),
+        ];
+        let packet_count = transactions.len();
+        // A single chunk so both votes land in one batch in the order given.
+        let packet_batches = to_packet_batches(&transactions, packet_count);
+
+        let votes = LatestUnprocessedVotes::new();
+        let packet_stats = PacketDeserializer::deserialize_and_buffer_votes(
+            packet_count,
+            &[BankingPacketBatch::new(packet_batches)],
+            &votes,
+        );
+
+        assert_eq!(packet_stats.votes_received_count, 2);
+        // The slot-3 vote arrives after the already-buffered slot-5 vote from
+        // the same validator, so it's pruned as stale.
+        assert_eq!(packet_stats.votes_pruned_stale_count, 1);
+        assert_eq!(votes.current_votes().len(), 1);
+    }
+
+    #[test]
+    fn test_receive_and_buffer_votes_round_trip() {
+        let vote_keypair = Keypair::new();
+        let packet_batches = to_packet_batches(&[vote_transaction_for_slot(&vote_keypair, 1)], 1);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        sender
+            .send(BankingPacketBatch::new(packet_batches))
+            .unwrap();
+
+        let deserializer = PacketDeserializer::new(receiver);
+        let votes = LatestUnprocessedVotes::new();
+        let packet_stats = deserializer
+            .receive_and_buffer_votes(Duration::from_millis(100), 16, &votes)
+            .unwrap();
+
+        assert_eq!(packet_stats.votes_received_count, 1);
+        assert_eq!(packet_stats.votes_pruned_stale_count, 0);
+        assert_eq!(votes.current_votes().len(), 1);
+    }
 }
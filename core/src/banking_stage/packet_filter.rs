@@ -0,0 +1,123 @@
+//! Cheap, stateless filters applied to a packet immediately after
+//! deserialization, before it is ever handed to a banking thread.
+
+use {
+    super::immutable_deserialized_packet::ImmutableDeserializedPacket,
+    solana_sdk::{bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable, compute_budget,
+        pubkey::Pubkey, saturating_add_assign, system_program, vote},
+    std::{collections::HashMap, sync::OnceLock},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PacketFilterFailure {
+    ExcessivePrecompiles,
+    InsufficientComputeLimit,
+    /// The transaction's requested compute unit limit is below the
+    /// statically-known cost of the builtin instructions it invokes, so it
+    /// can never produce a useful state change.
+    BelowStaticBuiltinCost,
+}
+
+/// Per-instruction costs for builtin programs that can be determined
+/// statically, without executing the transaction. Mirrors the costs the
+/// cost model would charge for these builtins regardless of their
+/// arguments.
+fn static_builtin_costs() -> &'static HashMap<Pubkey, u64> {
+    static COSTS: OnceLock<HashMap<Pubkey, u64>> = OnceLock::new();
+    COSTS.get_or_init(|| {
+        HashMap::from([
+            (system_program::id(), 150),
+            (vote::program::id(), 2_100),
+            (compute_budget::id(), 150),
+            (bpf_loader::id(), 2_370),
+            (bpf_loader_deprecated::id(), 1_140),
+            (bpf_loader_upgradeable::id(), 2_370),
+        ])
+    })
+}
+
+/// Rejects a transaction whose declared compute unit limit cannot possibly
+/// cover the statically-known cost of the builtin instructions it
+/// references. Such a transaction is guaranteed to fail during execution
+/// without ever producing a useful state change, so it is cheaper to reject
+/// it here than to carry it through to the banking threads.
+pub fn below_static_builtin_cost_filter(
+    packet: ImmutableDeserializedPacket,
+) -> Result<ImmutableDeserializedPacket, PacketFilterFailure> {
+    let costs = static_builtin_costs();
+    let mut required_cost: u64 = 0;
+    for (program_id, _instruction) in packet
+        .transaction()
+        .get_message()
+        .program_instructions_iter()
+    {
+        if let Some(cost) = costs.get(program_id) {
+            saturating_add_assign!(required_cost, *cost);
+        }
+    }
+
+    if packet.compute_unit_limit() < required_cost {
+        return Err(PacketFilterFailure::BelowStaticBuiltinCost);
+    }
+
+    Ok(packet)
+}
+
+// `below_static_builtin_cost_filter` is meant to be composed into the
+// `packet_filter` closure that `BankingStage` passes to
+// `PacketDeserializer::receive_packets` (e.g. by `Result::and_then`-chaining
+// it after the existing precompile/compute-limit filters). That call site
+// lives in `banking_stage.rs`, which is outside this series; wiring it in is
+// out of scope here.
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_perf::packet::Packet,
+        solana_sdk::{
+            compute_budget::ComputeBudgetInstruction,
+            hash::Hash,
+            message::Message,
+            signature::{Keypair, Signer},
+            system_instruction,
+            transaction::Transaction,
+        },
+    };
+
+    fn packet_with_compute_unit_limit(compute_unit_limit: Option<u32>) -> ImmutableDeserializedPacket {
+        let payer = Keypair::new();
+        let mut instructions = vec![];
+        if let Some(compute_unit_limit) = compute_unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                compute_unit_limit,
+            ));
+        }
+        instructions.push(system_instruction::transfer(
+            &payer.pubkey(),
+            &solana_sdk::pubkey::Pubkey::new_unique(),
+            1,
+        ));
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[&payer], message, Hash::default());
+        let packet = Packet::from_data(None, transaction).unwrap();
+        ImmutableDeserializedPacket::new(&packet).unwrap()
+    }
+
+    #[test]
+    fn test_below_static_builtin_cost_filter_rejects_underpriced_transaction() {
+        // A compute unit limit of 1 cannot cover even the system program's
+        // static builtin cost.
+        let packet = packet_with_compute_unit_limit(Some(1));
+        assert!(matches!(
+            below_static_builtin_cost_filter(packet),
+            Err(PacketFilterFailure::BelowStaticBuiltinCost)
+        ));
+    }
+
+    #[test]
+    fn test_below_static_builtin_cost_filter_accepts_well_priced_transaction() {
+        let packet = packet_with_compute_unit_limit(None);
+        assert!(below_static_builtin_cost_filter(packet).is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+//! A bounded priority buffer for deserialized packets, ordered by
+//! compute-unit price (ties broken by arrival order) with cheap access to
+//! both the highest- and lowest-priority packet.
+
+use {
+    super::immutable_deserialized_packet::ImmutableDeserializedPacket, min_max_heap::MinMaxHeap,
+    std::{cmp::Ordering, sync::Arc},
+};
+
+/// A packet paired with a monotonically increasing sequence number, so that
+/// packets of equal priority are still ordered deterministically (oldest
+/// first) instead of arbitrarily.
+#[derive(Clone)]
+struct PrioritizedPacket {
+    packet: Arc<ImmutableDeserializedPacket>,
+    sequence: u64,
+}
+
+impl PartialEq for PrioritizedPacket {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PrioritizedPacket {}
+
+impl PartialOrd for PrioritizedPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PrioritizedPacket {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.packet
+            .compute_unit_price()
+            .cmp(&other.packet.compute_unit_price())
+            // Ties go to the packet that arrived first.
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+/// Fixed-capacity buffer of packets ordered by priority. Once `capacity` is
+/// reached, inserting a new packet evicts the current lowest-priority
+/// packet instead of rejecting the newcomer outright.
+pub struct PacketPriorityQueue {
+    capacity: usize,
+    next_sequence: u64,
+    heap: MinMaxHeap<PrioritizedPacket>,
+}
+
+impl PacketPriorityQueue {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_sequence: 0,
+            heap: MinMaxHeap::with_capacity(capacity),
+        }
+    }
+
+    /// Inserts `packet`, returning the packet evicted to make room for it,
+    /// if any. The newcomer itself is returned if the buffer is full and
+    /// the newcomer is the lowest priority packet in the buffer.
+    pub fn push(
+        &mut self,
+        packet: ImmutableDeserializedPacket,
+    ) -> Option<Arc<ImmutableDeserializedPacket>> {
+        let entry = PrioritizedPacket {
+            packet: Arc::new(packet),
+            sequence: self.next_sequence,
+        };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        // For `capacity == 0` this always takes the `push_pop_min` branch,
+        // which inserts `entry` and immediately pops it back out again as
+        // the minimum of a one-element heap, so every insert is rejected
+        // rather than growing the buffer unboundedly.
+        let evicted = if self.heap.len() >= self.capacity {
+            Some(self.heap.push_pop_min(entry))
+        } else {
+            self.heap.push(entry);
+            None
+        };
+        evicted.map(|entry| entry.packet)
+    }
+
+    /// Removes and returns the highest-priority packet.
+    pub fn pop_max(&mut self) -> Option<Arc<ImmutableDeserializedPacket>> {
+        self.heap.pop_max().map(|entry| entry.packet)
+    }
+
+    /// Removes and returns the lowest-priority packet.
+    pub fn pop_min(&mut self) -> Option<Arc<ImmutableDeserializedPacket>> {
+        self.heap.pop_min().map(|entry| entry.packet)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Drains the buffer, yielding packets from highest to lowest priority.
+    pub fn drain_desc(&mut self) -> impl Iterator<Item = Arc<ImmutableDeserializedPacket>> + '_ {
+        std::iter::from_fn(move || self.heap.pop_max()).map(|entry| entry.packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_perf::packet::Packet,
+        solana_sdk::{
+            compute_budget::ComputeBudgetInstruction, hash::Hash, message::Message,
+            signature::Keypair, signer::Signer, system_instruction, transaction::Transaction,
+        },
+    };
+
+    fn packet_with_price(compute_unit_price: u64) -> ImmutableDeserializedPacket {
+        let payer = Keypair::new();
+        let instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+            system_instruction::transfer(&payer.pubkey(), &solana_sdk::pubkey::Pubkey::new_unique(), 1),
+        ];
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[&payer], message, Hash::default());
+        let packet = Packet::from_data(None, transaction).unwrap();
+        ImmutableDeserializedPacket::new(&packet).unwrap()
+    }
+
+    #[test]
+    fn test_push_below_capacity_does_not_evict() {
+        let mut queue = PacketPriorityQueue::with_capacity(2);
+        assert!(queue.push(packet_with_price(1)).is_none());
+        assert!(queue.push(packet_with_price(2)).is_none());
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_push_past_capacity_evicts_true_minimum() {
+        let mut queue = PacketPriorityQueue::with_capacity(2);
+        assert!(queue.push(packet_with_price(5)).is_none());
+        assert!(queue.push(packet_with_price(10)).is_none());
+
+        // The buffer is full; inserting a packet priced between the two
+        // existing ones should evict the lowest-priced entry (5), not the
+        // newcomer.
+        let evicted = queue.push(packet_with_price(7)).unwrap();
+        assert_eq!(evicted.compute_unit_price(), 5);
+        assert_eq!(queue.len(), 2);
+
+        // Inserting something cheaper than everything currently buffered
+        // evicts the newcomer itself.
+        let evicted = queue.push(packet_with_price(1)).unwrap();
+        assert_eq!(evicted.compute_unit_price(), 1);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_pop_max_and_pop_min_ordering() {
+        let mut queue = PacketPriorityQueue::with_capacity(3);
+        queue.push(packet_with_price(5));
+        queue.push(packet_with_price(1));
+        queue.push(packet_with_price(10));
+
+        assert_eq!(queue.pop_max().unwrap().compute_unit_price(), 10);
+        assert_eq!(queue.pop_min().unwrap().compute_unit_price(), 1);
+        assert_eq!(queue.pop_max().unwrap().compute_unit_price(), 5);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_tie_break_favors_earlier_arrival() {
+        let mut queue = PacketPriorityQueue::with_capacity(3);
+        let earlier = packet_with_price(5);
+        let earlier_signature = *earlier.transaction().signature();
+        queue.push(earlier);
+        queue.push(packet_with_price(5));
+
+        // Among equal-priority packets, the one pushed first is treated as
+        // higher priority (processed/retained before the later arrival).
+        // Each packet is signed by its own freshly-generated payer, so
+        // comparing signatures (rather than the shared compute_unit_price)
+        // actually distinguishes them.
+        let first = queue.pop_max().unwrap();
+        assert_eq!(*first.transaction().signature(), earlier_signature);
+    }
+
+    #[test]
+    fn test_zero_capacity_rejects_every_insert() {
+        let mut queue = PacketPriorityQueue::with_capacity(0);
+        let evicted = queue.push(packet_with_price(5)).unwrap();
+        assert_eq!(evicted.compute_unit_price(), 5);
+        assert_eq!(queue.len(), 0);
+    }
+}
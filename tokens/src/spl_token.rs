@@ -1,13 +1,15 @@
 use {
     crate::{
         args::{DistributeTokensArgs, SplTokenArgs},
-        commands::{get_fee_estimate_for_messages, Error, FundingSource, TypedAllocation},
+        commands::{Error, FundingSource, TypedAllocation},
     },
     console::style,
+    solana_account::Account,
     solana_account_decoder::parse_token::{real_number_string, real_number_string_trimmed},
     solana_instruction::Instruction,
     solana_message::Message,
     solana_native_token::lamports_to_sol,
+    solana_pubkey::Pubkey,
     solana_rpc_client::rpc_client::RpcClient,
     spl_associated_token_account_interface::{
         address::get_associated_token_address, instruction::create_associated_token_account,
@@ -16,22 +18,178 @@ use {
         solana_program::program_pack::Pack,
         state::{Account as SplTokenAccount, Mint},
     },
+    std::fmt,
 };
 
-pub fn update_token_args(client: &RpcClient, args: &mut Option<SplTokenArgs>) -> Result<(), Error> {
+/// Error produced by a [`SplTokenAccountClient`] implementation, covering
+/// both live RPC failures and in-process `BanksClient` failures under a
+/// single type so the `spl_token` functions can stay generic over either.
+#[derive(Debug)]
+pub enum SplTokenClientError {
+    Rpc(String),
+}
+
+impl fmt::Display for SplTokenClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplTokenClientError::Rpc(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for SplTokenClientError {}
+
+impl From<solana_rpc_client_api::client_error::Error> for SplTokenClientError {
+    fn from(err: solana_rpc_client_api::client_error::Error) -> Self {
+        SplTokenClientError::Rpc(err.to_string())
+    }
+}
+
+impl From<SplTokenClientError> for Error {
+    fn from(err: SplTokenClientError) -> Self {
+        Error::RpcClientError(err.to_string())
+    }
+}
+
+/// Abstracts the account-fetch, balance, fee-estimate, and rent-exemption
+/// calls the `spl_token` functions need, so that the allocation/transfer/
+/// balance-check logic can be driven by either a live `RpcClient` or an
+/// in-process `BanksClient` backed by a `ProgramTest` bank.
+pub trait SplTokenAccountClient {
+    /// Returns the account at `pubkey`, or a default (empty, zero-lamport)
+    /// `Account` if it doesn't exist *or* if fetching it failed. Callers
+    /// that need to distinguish "account not yet created" from "RPC/
+    /// transport error" should use [`SplTokenAccountClient::get_balance`]
+    /// instead, which surfaces failures as `Err`.
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Account, SplTokenClientError>;
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64, SplTokenClientError>;
+    fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, SplTokenClientError>;
+    fn get_fee_for_message(&self, message: &Message) -> Result<u64, SplTokenClientError>;
+}
+
+impl SplTokenAccountClient for RpcClient {
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Account, SplTokenClientError> {
+        Ok(RpcClient::get_account(self, pubkey).unwrap_or_default())
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64, SplTokenClientError> {
+        Ok(RpcClient::get_balance(self, pubkey)?)
+    }
+
+    fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, SplTokenClientError> {
+        Ok(RpcClient::get_minimum_balance_for_rent_exemption(
+            self, data_len,
+        )?)
+    }
+
+    fn get_fee_for_message(&self, message: &Message) -> Result<u64, SplTokenClientError> {
+        Ok(RpcClient::get_fee_for_message(self, message)?)
+    }
+}
+
+/// Bridges the async `BanksClient` to the synchronous `SplTokenAccountClient`
+/// trait, so the allocation/transfer/balance-check functions in this module
+/// can be driven by an in-process `ProgramTest` bank instead of a live
+/// `RpcClient` (e.g. from another crate's own `BanksClient`-backed tests).
+///
+/// Owns the `Runtime` it drives `BanksClient` with and only ever calls
+/// `block_on` on it directly from non-`async` callers; nesting that
+/// `block_on` inside another runtime's `block_on` panics, so this must not be
+/// driven from within `#[tokio::test]` or any other already-async context.
+#[cfg(feature = "dev-context-only-utils")]
+pub struct BanksTokenClient {
+    client: std::sync::Mutex<std::cell::RefCell<solana_banks_client::BanksClient>>,
+    runtime: solana_program_test::tokio::runtime::Runtime,
+    /// The funded payer keypair `ProgramTest` generated at genesis, kept
+    /// around so callers can query balances without standing up a second
+    /// bank.
+    pub payer: solana_keypair::Keypair,
+}
+
+#[cfg(feature = "dev-context-only-utils")]
+impl BanksTokenClient {
+    /// Starts `program_test` and wraps the resulting `BanksClient`, doing the
+    /// one-time async setup on the same runtime that will later back every
+    /// synchronous trait call.
+    pub fn start(program_test: solana_program_test::ProgramTest) -> Self {
+        let runtime = solana_program_test::tokio::runtime::Runtime::new().unwrap();
+        let (banks_client, payer, _recent_blockhash) = runtime.block_on(program_test.start());
+        Self {
+            client: std::sync::Mutex::new(std::cell::RefCell::new(banks_client)),
+            runtime,
+            payer,
+        }
+    }
+}
+
+#[cfg(feature = "dev-context-only-utils")]
+impl SplTokenAccountClient for BanksTokenClient {
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Account, SplTokenClientError> {
+        let client = self.client.lock().unwrap();
+        // Matches the `RpcClient` impl: a missing account or a transport
+        // failure both surface as a default, empty `Account` rather than an
+        // error, since callers already treat "no account" and "couldn't
+        // fetch it" the same way.
+        Ok(self
+            .runtime
+            .block_on(client.borrow_mut().get_account(*pubkey))
+            .ok()
+            .flatten()
+            .unwrap_or_default())
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64, SplTokenClientError> {
+        let client = self.client.lock().unwrap();
+        self.runtime
+            .block_on(client.borrow_mut().get_balance(*pubkey))
+            .map_err(|err| SplTokenClientError::Rpc(err.to_string()))
+    }
+
+    fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, SplTokenClientError> {
+        let client = self.client.lock().unwrap();
+        let rent = self
+            .runtime
+            .block_on(client.borrow_mut().get_rent())
+            .map_err(|err| SplTokenClientError::Rpc(err.to_string()))?;
+        Ok(rent.minimum_balance(data_len))
+    }
+
+    fn get_fee_for_message(&self, message: &Message) -> Result<u64, SplTokenClientError> {
+        let client = self.client.lock().unwrap();
+        self.runtime
+            .block_on(client.borrow_mut().get_fee_for_message(message.clone()))
+            .map_err(|err| SplTokenClientError::Rpc(err.to_string()))?
+            .ok_or_else(|| SplTokenClientError::Rpc("fee estimate unavailable".to_string()))
+    }
+}
+
+pub fn update_token_args(
+    client: &impl SplTokenAccountClient,
+    args: &mut Option<SplTokenArgs>,
+) -> Result<(), Error> {
     if let Some(spl_token_args) = args {
-        let sender_account = client
-            .get_account(&spl_token_args.token_account_address)
-            .unwrap_or_default();
+        let sender_account = client.get_account(&spl_token_args.token_account_address)?;
         spl_token_args.mint = SplTokenAccount::unpack(&sender_account.data)?.mint;
         update_decimals(client, args)?;
     }
     Ok(())
 }
 
-pub fn update_decimals(client: &RpcClient, args: &mut Option<SplTokenArgs>) -> Result<(), Error> {
+pub fn update_decimals(
+    client: &impl SplTokenAccountClient,
+    args: &mut Option<SplTokenArgs>,
+) -> Result<(), Error> {
     if let Some(spl_token_args) = args {
-        let mint_account = client.get_account(&spl_token_args.mint).unwrap_or_default();
+        let mint_account = client.get_account(&spl_token_args.mint)?;
         let mint = Mint::unpack(&mint_account.data)?;
         spl_token_args.decimals = mint.decimals;
     }
@@ -78,7 +236,7 @@ pub(crate) fn build_spl_token_instructions(
 pub(crate) fn check_spl_token_balances(
     messages: &[Message],
     allocations: &[TypedAllocation],
-    client: &RpcClient,
+    client: &impl SplTokenAccountClient,
     args: &DistributeTokensArgs,
     created_accounts: u64,
 ) -> Result<(), Error> {
@@ -87,7 +245,12 @@ pub(crate) fn check_spl_token_balances(
         .as_ref()
         .expect("spl_token_args must be some");
     let allocation_amount: u64 = allocations.iter().map(|x| x.amount).sum();
-    let fees = get_fee_estimate_for_messages(messages, client)?;
+    let fees = messages
+        .iter()
+        .map(|message| client.get_fee_for_message(message))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .sum::<u64>();
 
     let token_account_rent_exempt_balance =
         client.get_minimum_balance_for_rent_exemption(SplTokenAccount::LEN)?;
@@ -99,9 +262,7 @@ pub(crate) fn check_spl_token_balances(
             lamports_to_sol(fees + account_creation_amount).to_string(),
         ));
     }
-    let source_token_account = client
-        .get_account(&spl_token_args.token_account_address)
-        .unwrap_or_default();
+    let source_token_account = client.get_account(&spl_token_args.token_account_address)?;
     let source_token = SplTokenAccount::unpack(&source_token_account.data)?;
     if source_token.amount < allocation_amount {
         return Err(Error::InsufficientFunds(
@@ -113,16 +274,14 @@ pub(crate) fn check_spl_token_balances(
 }
 
 pub(crate) fn print_token_balances(
-    client: &RpcClient,
+    client: &impl SplTokenAccountClient,
     allocation: &TypedAllocation,
     spl_token_args: &SplTokenArgs,
 ) -> Result<(), Error> {
     let address = allocation.recipient;
     let expected = allocation.amount;
     let associated_token_address = get_associated_token_address(&address, &spl_token_args.mint);
-    let recipient_account = client
-        .get_account(&associated_token_address)
-        .unwrap_or_default();
+    let recipient_account = client.get_account(&associated_token_address)?;
     let (actual, difference) = if let Ok(recipient_token) =
         SplTokenAccount::unpack(&recipient_account.data)
     {
@@ -149,17 +308,61 @@ pub(crate) fn print_token_balances(
     Ok(())
 }
 
-#[cfg(test)]
+// This module's original v1.4 unit tests drove `update_token_args`,
+// `check_spl_token_balances`, `build_spl_token_instructions`, and
+// `print_token_balances` against a `ProgramTest`-backed `BanksClient`. They
+// were deleted, rather than rewritten, when this module was reverted to
+// `RpcClient` only (https://github.com/solana-labs/solana/pull/13623).
+// `BanksTokenClient` above restores the ability to drive this module's
+// account-fetch/balance/fee-estimate/rent-exemption calls against that same
+// kind of bank. Reinstating those tests themselves needs
+// `args::DistributeTokensArgs`, `args::SplTokenArgs`, `commands::Error`, and
+// `commands::TypedAllocation` construction, and this tree doesn't contain the
+// `args.rs`/`commands.rs` files that define those types, so their field
+// layouts can't be verified here; that part of the restoration stays
+// unimplemented until those files are present.
+//
+// https://github.com/solana-labs/solana/blob/5511d52c6284013a24ced10966d11d8f4585799e/tokens/src/spl_token.rs#L490-L685
+
+#[cfg(all(test, feature = "dev-context-only-utils"))]
 mod tests {
-    // The following unit tests were written for v1.4 using the ProgramTest framework, passing its
-    // BanksClient into the `solana-tokens` methods. With the revert to RpcClient in this module
-    // (https://github.com/solana-labs/solana/pull/13623), that approach was no longer viable.
-    // These tests were removed rather than rewritten to avoid accruing technical debt. Once a new
-    // rpc/client framework is implemented, they should be restored.
-    //
-    // async fn test_process_spl_token_allocations()
-    // async fn test_process_spl_token_transfer_amount_allocations()
-    // async fn test_check_spl_token_balances()
-    //
-    // https://github.com/solana-labs/solana/blob/5511d52c6284013a24ced10966d11d8f4585799e/tokens/src/spl_token.rs#L490-L685
+    use {
+        super::*,
+        solana_program_test::{processor, ProgramTest},
+        solana_signer::Signer,
+    };
+
+    fn program_test_client() -> BanksTokenClient {
+        BanksTokenClient::start(ProgramTest::new(
+            "spl_token",
+            spl_token::id(),
+            processor!(spl_token::processor::Processor::process),
+        ))
+    }
+
+    #[test]
+    fn test_banks_client_get_minimum_balance_for_rent_exemption() {
+        let client = program_test_client();
+        let rent_exempt_balance = client
+            .get_minimum_balance_for_rent_exemption(SplTokenAccount::LEN)
+            .unwrap();
+        assert!(rent_exempt_balance > 0);
+    }
+
+    #[test]
+    fn test_banks_client_get_balance_of_funded_account() {
+        let client = program_test_client();
+        // `ProgramTest` funds its payer with a large lamport balance at
+        // genesis.
+        let payer = client.payer.pubkey();
+        let balance = client.get_balance(&payer).unwrap();
+        assert!(balance > 0);
+    }
+
+    #[test]
+    fn test_banks_client_get_account_of_missing_account_defaults() {
+        let client = program_test_client();
+        let account = client.get_account(&Pubkey::new_unique()).unwrap();
+        assert_eq!(account, Account::default());
+    }
 }